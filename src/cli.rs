@@ -3,26 +3,167 @@
 use {
     std::{
         cmp::Ordering::*,
+        collections::VecDeque,
         fmt,
         future::Future,
         io::{
             Stdout,
+            Write as _,
             stdout,
         },
+        sync::Arc,
+        time::{
+            Duration,
+            Instant,
+        },
     },
     crossterm::terminal::{
         ClearType,
         disable_raw_mode,
         enable_raw_mode,
     },
+    futures::stream::StreamExt as _,
     parking_lot::Mutex,
     tokio::{
         io,
         sync::broadcast,
     },
-    crate::Task,
+    unicode_segmentation::UnicodeSegmentation as _,
+    unicode_width::{
+        UnicodeWidthChar as _,
+        UnicodeWidthStr as _,
+    },
+    crate::{
+        Percent,
+        Task,
+    },
 };
 
+/// The ellipsis appended to a label that had to be truncated to fit the terminal.
+const ELLIPSIS: char = '…';
+
+/// Strips embedded newlines and other control characters from `label`, then truncates it (by grapheme cluster, not
+/// byte or `char`) so its rendered width fits within `max_width` columns, appending [`ELLIPSIS`] if it had to cut
+/// anything off.
+fn sanitize_and_truncate(label: &str, max_width: u16) -> String {
+    let max_width = usize::from(max_width);
+    let sanitized = label.chars().filter(|c| !c.is_control()).collect::<String>();
+    if sanitized.width() <= max_width {
+        return sanitized
+    }
+    if max_width == 0 {
+        return String::new()
+    }
+    let budget = max_width - 1; // leave room for the ellipsis
+    let mut truncated = String::new();
+    let mut width = 0;
+    for grapheme in sanitized.graphemes(true) {
+        let grapheme_width = grapheme.width();
+        if width + grapheme_width > budget {
+            break
+        }
+        width += grapheme_width;
+        truncated.push_str(grapheme);
+    }
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+/// The number of samples kept by an [`Estimator`], i.e. how far back it looks to compute a throughput.
+const ESTIMATOR_WINDOW: usize = 32;
+
+/// Tracks recent progress samples for a single task, used to estimate its throughput and time remaining.
+#[derive(Debug, Default)]
+struct Estimator {
+    /// The oldest sample is at the front, the most recent at the back.
+    samples: VecDeque<(Instant, Percent)>,
+}
+
+impl Estimator {
+    fn push(&mut self, percent: Percent) {
+        if self.samples.len() == ESTIMATOR_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((Instant::now(), percent));
+    }
+
+    /// Returns the estimated percent per second, based on the oldest and newest samples in the window.
+    ///
+    /// Returns `None` until at least two samples with distinct percentages are available.
+    fn rate(&self) -> Option<f64> {
+        let (oldest_time, oldest_percent) = *self.samples.front()?;
+        let (latest_time, latest_percent) = *self.samples.back()?;
+        if oldest_percent == latest_percent { return None }
+        let elapsed = latest_time.duration_since(oldest_time).as_secs_f64();
+        if elapsed <= 0.0 { return None }
+        Some((f64::from(u8::from(latest_percent)) - f64::from(u8::from(oldest_percent))) / elapsed)
+    }
+
+    /// Returns the estimated time remaining, based on the current rate and the most recent sample.
+    ///
+    /// Returns `None` if the rate is zero, negative (i.e. progress has stalled or regressed), or not yet known.
+    fn eta(&self) -> Option<Duration> {
+        let rate = self.rate()?;
+        if rate <= 0.0 { return None }
+        let (_, latest_percent) = *self.samples.back()?;
+        let remaining = 100.0 - f64::from(u8::from(latest_percent));
+        Some(Duration::from_secs_f64(remaining / rate))
+    }
+
+    /// Renders this estimator's current rate and ETA as a `(N.N%/s, ETA mm:ss)` suffix.
+    fn suffix(&self) -> String {
+        let rate = self.rate().unwrap_or_default();
+        let eta = self.eta().map_or_else(|| "--:--".to_owned(), |eta| {
+            let total_secs = eta.as_secs();
+            format!("{:02}:{:02}", total_secs / 60, total_secs % 60)
+        });
+        format!(" ({rate:.1}%/s, ETA {eta})")
+    }
+}
+
+/// The default refresh rate used by [`Cli::new`], in hertz.
+///
+/// Chosen to be fast enough that progress still looks smooth to a human, while capping the number of redraws a
+/// fast-ticking task can trigger per second.
+const DEFAULT_REFRESH_RATE: f64 = 15.0;
+
+/// A leaky bucket used to throttle redraws of a single line.
+///
+/// `capacity` starts full, so the first throttled redraw of a line is never delayed. Each redraw that goes through
+/// spends one unit of capacity, which then refills at `refill_per_sec`; capacity never exceeds one unit, so at most
+/// one redraw goes through per `1 / refill_per_sec` seconds.
+#[derive(Debug, Clone, Copy)]
+struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(refill_per_sec: f64) -> Self {
+        Self {
+            capacity: 1.0,
+            refill_per_sec,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Returns `true` if a redraw should happen now, spending capacity in that case. Returns `false` if the redraw
+    /// should be skipped, since the most recent one happened too recently.
+    fn try_consume(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.capacity = (self.capacity + elapsed * self.refill_per_sec).min(1.0);
+        if self.capacity >= 1.0 {
+            self.capacity -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
 #[derive(Debug)]
 struct State {
     lines: Vec<LineState>,
@@ -30,34 +171,62 @@ struct State {
     new_line_id: LineId,
     finalize_notifier: broadcast::Sender<()>,
     stdout: Stdout,
+    /// `None` means redraws are never throttled.
+    refresh_rate: Option<f64>,
+    /// Whether `run`/`run_with` should append a throughput/ETA suffix to the progress line.
+    show_eta: bool,
+    /// The last known terminal size, as `(cols, rows)`, kept up to date via [`crossterm::event::Event::Resize`].
+    term_size: (u16, u16),
+    /// `None` means `run`/`run_with` render a plain `[ 42%]` indicator; `Some` means they render a graphical bar.
+    bar_style: Option<BarStyle>,
 }
 
 impl State {
-    fn update_line(&mut self, id: LineId) -> io::Result<()> {
-        let (self_idx, line) = self.lines.iter().enumerate().find(|(_, line)| line.id == id).expect("line not found");
+    /// Redraws the line with the given ID.
+    ///
+    /// Unless `force` is set, the redraw may be skipped (keeping the line's text up to date internally, but not
+    /// actually touching the terminal) if it's been throttled by the configured refresh rate. `force` must be set
+    /// for structural changes (new or reordered lines) and for a line's final state, so those are never dropped.
+    ///
+    /// Only queues the commands for this redraw; callers are responsible for flushing `self.stdout` once they're
+    /// done with their whole redraw pass, so e.g. rearranging several lines at once only causes a single flush.
+    fn update_line(&mut self, id: LineId, force: bool) -> io::Result<()> {
+        let self_idx = self.lines.iter().position(|line| line.id == id).expect("line not found");
+        if !force {
+            if let Some(refresh_rate) = self.refresh_rate {
+                let line = &mut self.lines[self_idx];
+                let limiter = line.rate_limiter.get_or_insert_with(|| RateLimiter::new(refresh_rate));
+                if !limiter.try_consume() {
+                    return Ok(())
+                }
+            }
+        }
+        let line = &self.lines[self_idx];
+        let available_width = self.term_size.0.saturating_sub(line.prefix.width().try_into().unwrap_or(u16::MAX));
+        let rendered = format!("{}{}", line.prefix, sanitize_and_truncate(&line.label, available_width));
         let selected_idx = self.selected_line.map_or_else(|| self.lines.len(), |selected_line| self.lines.iter().position(|line| line.id == selected_line).expect("line not found"));
         match self_idx.cmp(&selected_idx) {
             Less => {
                 let line_diff = selected_idx - self_idx;
-                crossterm::execute!(
+                crossterm::queue!(
                     self.stdout,
                     crossterm::cursor::MoveToPreviousLine(line_diff.try_into().expect("terminal too large")),
-                    crossterm::style::Print(&line.text),
+                    crossterm::style::Print(rendered),
                     crossterm::terminal::Clear(ClearType::UntilNewLine),
                 )?;
             }
-            Equal => crossterm::execute!(
+            Equal => crossterm::queue!(
                 self.stdout,
                 crossterm::cursor::MoveToColumn(0),
-                crossterm::style::Print(&line.text),
+                crossterm::style::Print(rendered),
                 crossterm::terminal::Clear(ClearType::UntilNewLine),
             )?,
             Greater => {
                 let line_diff = self_idx - selected_idx;
-                crossterm::execute!(
+                crossterm::queue!(
                     self.stdout,
                     crossterm::cursor::MoveToNextLine(line_diff.try_into().expect("terminal too large")),
-                    crossterm::style::Print(&line.text),
+                    crossterm::style::Print(rendered),
                     crossterm::terminal::Clear(ClearType::UntilNewLine),
                 )?;
             }
@@ -65,6 +234,126 @@ impl State {
         self.selected_line = Some(id);
         Ok(())
     }
+
+    /// Tries to make room for one more line at the bottom of the terminal, by forgetting about or rearranging
+    /// already-finalized lines as needed, reusing `self.term_size` as the known terminal height.
+    ///
+    /// Returns `true` once there is room. Returns `false` if there isn't, and there are no finalized lines left to
+    /// reclaim room from, so the caller has to wait for a line to finalize or for the terminal to grow.
+    ///
+    /// Only queues commands; callers are responsible for flushing `self.stdout` afterwards.
+    fn make_room(&mut self) -> io::Result<bool> {
+        loop {
+            if u16::try_from(self.lines.len()).expect("terminal too large") < self.term_size.1 {
+                // There is room on the terminal for a new line.
+                return Ok(true)
+            }
+            if let Some(&LineState { finalized: true, id, .. }) = self.lines.get(0) {
+                // There is a finalized line at the top of the CLI. Forget about this line, letting it scroll off the top of the screen.
+                if self.selected_line == Some(id) {
+                    if let Some(next_line) = self.lines.get(1) {
+                        let next_id = next_line.id;
+                        crossterm::queue!(
+                            self.stdout,
+                            crossterm::cursor::MoveToNextLine(1),
+                        )?;
+                        self.selected_line = Some(next_id);
+                    } else {
+                        crossterm::queue!(
+                            self.stdout,
+                            crossterm::style::Print("\r\n"),
+                        )?;
+                        self.selected_line = None;
+                    }
+                }
+                self.lines.remove(0);
+                continue
+            }
+            if let Some(idx) = self.lines.iter().position(|line| line.finalized) {
+                // There is a finalized line below some unfinalized lines. Rearrange the lines to move the finalized line to the top so it can be forgotten about in the next iteration of the loop.
+                let line = self.lines.remove(idx);
+                self.lines.insert(0, line);
+                for line_id in self.lines[..=idx].iter().map(|line| line.id).collect::<Vec<_>>() {
+                    self.update_line(line_id, true)?;
+                }
+                continue
+            }
+            // No room and no finalized lines. The caller must wait.
+            return Ok(false)
+        }
+    }
+}
+
+/// Columns reserved for the task's own label when sizing a progress bar, so it doesn't crowd the label out entirely.
+const MIN_LABEL_WIDTH: u16 = 10;
+
+/// The characters used to render a graphical progress bar, as an alternative to the plain numeric percentage.
+///
+/// Used with [`Cli::with_bar`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarStyle {
+    /// Drawn for the portion of the bar that represents completed progress.
+    pub filled: char,
+    /// Drawn for the remaining, not yet completed portion of the bar.
+    pub empty: char,
+    /// Drawn once, before the filled and empty cells.
+    pub start: char,
+    /// Drawn once, after the filled and empty cells.
+    pub end: char,
+}
+
+impl Default for BarStyle {
+    fn default() -> Self {
+        Self { filled: '=', empty: '-', start: '[', end: ']' }
+    }
+}
+
+/// Renders a `[=====-----] 50%`-style prefix for the given percentage, sizing the bar to fit `term_cols`.
+///
+/// The bar shrinks toward zero width as `term_cols` shrinks, rather than being held to a hard minimum that could
+/// end up wider than the terminal. On a terminal too narrow to even fit the brackets and percentage, the result is
+/// additionally hard-truncated to `term_cols` columns, so the line-rewriting cursor math in [`State::update_line`]
+/// never has to deal with a rendered prefix wider than the terminal.
+fn render_bar(style: BarStyle, percent: Percent, term_cols: u16) -> String {
+    let reserved = 2 /* start + end */ + 1 /* space */ + 4 /* "100%" */ + 1 /* trailing space */ + MIN_LABEL_WIDTH;
+    let width = term_cols.saturating_sub(reserved);
+    let filled = u16::try_from(u32::from(width) * u32::from(u8::from(percent)) / 100).unwrap_or(width);
+    let empty = width - filled;
+    let rendered = format!(
+        "{}{}{}{} {}% ",
+        style.start,
+        style.filled.to_string().repeat(filled.into()),
+        style.empty.to_string().repeat(empty.into()),
+        style.end,
+        u8::from(percent),
+    );
+    let mut cols = 0;
+    rendered.chars().take_while(|c| {
+        cols += c.width().unwrap_or(0);
+        cols <= usize::from(term_cols)
+    }).collect()
+}
+
+/// Runs for as long as the owning [`Cli`] does, keeping [`State::term_size`] up to date and making room for
+/// already-blocked lines as the terminal is resized, regardless of whether anything is currently waiting in
+/// [`Cli::new_line_with_prefix`].
+///
+/// There is only ever one of these per `Cli`, since [`crossterm::event::EventStream`] only reliably supports a
+/// single live instance per process; callers that need to react to a resize (or to a line finalizing) share this
+/// one instance's updates via `state.finalize_notifier` instead of creating their own.
+async fn watch_resize(state: Arc<Mutex<State>>) {
+    let mut resize_events = crossterm::event::EventStream::new();
+    while let Some(event) = resize_events.next().await {
+        if let Ok(crossterm::event::Event::Resize(cols, rows)) = event {
+            let mut state = state.lock();
+            state.term_size = (cols, rows);
+            let _ = state.make_room();
+            let _ = state.stdout.flush();
+            // A blocked new_line may now have room (if the terminal grew) or some lines may have scrolled off (if
+            // it shrank); either way, wake up anyone waiting on a line.
+            let _ = state.finalize_notifier.send(());
+        }
+    }
 }
 
 /// A command-line progress renderer.
@@ -72,7 +361,9 @@ impl State {
 /// `Cli` does not implement [`Clone`]. If you need to share it across threads, consider wrapping it inside an [`Arc`](std::sync::Arc).
 #[derive(Debug)]
 pub struct Cli {
-    state: Mutex<State>,
+    state: Arc<Mutex<State>>,
+    /// Keeps [`State::term_size`] current; aborted when this `Cli` is dropped.
+    resize_task: tokio::task::JoinHandle<()>,
 }
 
 impl Cli {
@@ -83,109 +374,120 @@ impl Cli {
     /// If the height of the terminal cannot be determined.
     pub fn new() -> io::Result<Self> {
         enable_raw_mode()?;
-        Ok(Self {
-            state: Mutex::new(State {
-                lines: Vec::default(),
-                selected_line: None,
-                new_line_id: LineId(0),
-                finalize_notifier: broadcast::channel(1_024).0,
-                stdout: stdout(),
-            }),
-        })
+        let term_size = crossterm::terminal::size()?;
+        let state = Arc::new(Mutex::new(State {
+            lines: Vec::default(),
+            selected_line: None,
+            new_line_id: LineId(0),
+            finalize_notifier: broadcast::channel(1_024).0,
+            stdout: stdout(),
+            refresh_rate: Some(DEFAULT_REFRESH_RATE),
+            show_eta: false,
+            term_size,
+            bar_style: None,
+        }));
+        let resize_task = tokio::spawn(watch_resize(Arc::clone(&state)));
+        Ok(Self { state, resize_task })
     }
 
-    /// Waits until space is available at the bottom of the terminal, then creates a new line and returns a handle to it.
+    /// Sets the maximum rate, in hertz, at which a single line is redrawn.
     ///
-    /// # Correctness
+    /// A line's first progress update is drawn immediately, and structural redraws (new or reordered lines) and a
+    /// line's final state are never throttled, but rapid-fire updates beyond this rate are smoothed out, with only
+    /// the latest text shown once the rate limit allows another redraw. Pass `hz <= 0.0` to disable throttling
+    /// entirely.
     ///
-    /// If `initial_text` is wider than the terminal or contains newlines or other control codes, the entire `Cli` may display incorrectly.
+    /// The default, used if this is never called, is 15 Hz.
+    #[must_use]
+    pub fn with_refresh_rate(self, hz: f64) -> Self {
+        self.state.lock().refresh_rate = if hz > 0.0 { Some(hz) } else { None };
+        self
+    }
+
+    /// If `enabled`, `run`/`run_with` append a moving-average throughput and ETA suffix to the progress line, e.g.
+    /// `[ 42%] label (1.3%/s, ETA 00:12)`.
+    ///
+    /// Disabled by default, so existing callers keep the current plain output.
+    #[must_use]
+    pub fn with_eta(self, enabled: bool) -> Self {
+        self.state.lock().show_eta = enabled;
+        self
+    }
+
+    /// Renders `run`/`run_with` progress as a graphical bar, e.g. `[=====-----] 50%`, instead of the default plain
+    /// `[ 42%]` indicator. The bar's width adapts to the terminal width as it's resized.
+    #[must_use]
+    pub fn with_bar(self, style: BarStyle) -> Self {
+        self.state.lock().bar_style = Some(style);
+        self
+    }
+
+    /// Waits until space is available at the bottom of the terminal, then creates a new line and returns a handle to it.
+    ///
+    /// `initial_text` is sanitized (control characters and newlines are stripped) and truncated with an ellipsis if
+    /// it doesn't fit the terminal width; it is re-truncated on every redraw, so it adapts to terminal resizes.
     pub fn new_line<'a>(&'a self, initial_text: impl fmt::Display) -> impl Future<Output = io::Result<LineHandle<'a>>> + Send {
-        let text = initial_text.to_string();
-        async {
-            // make room for the line
-            loop {
-                let terminal_height = crossterm::terminal::size()?.1;
-                let mut notifications = {
-                    let mut state = self.state.lock();
-                    if u16::try_from(state.lines.len()).expect("terminal too large") < terminal_height {
-                        // There is room on the terminal for a new line.
-                        break
-                    }
-                    if let Some(&LineState { finalized: true, id, .. }) = state.lines.get(0) {
-                        // There is a finalized line at the top of the CLI. Forget about this line, letting it scroll off the top of the screen.
-                        if state.selected_line == Some(id) {
-                            if let Some(next_line) = state.lines.get(1) {
-                                let next_id = next_line.id;
-                                crossterm::execute!(
-                                    &mut state.stdout,
-                                    crossterm::cursor::MoveToNextLine(1),
-                                )?;
-                                state.selected_line = Some(next_id);
-                            } else {
-                                crossterm::execute!(
-                                    &mut state.stdout,
-                                    crossterm::style::Print("\r\n"),
-                                )?;
-                                state.selected_line = None;
-                            }
-                        }
-                        state.lines.remove(0);
-                        continue
-                    }
-                    if let Some(idx) = state.lines.iter().position(|line| line.finalized) {
-                        // There is a finalized line below some unfinalized lines. Rearrange the lines to move the finalized line to the top so it can be forgotten about in the next iteration of the loop.
-                        let line = state.lines.remove(idx);
-                        state.lines.insert(0, line);
-                        for line_id in state.lines[..=idx].iter().map(|line| line.id).collect::<Vec<_>>() {
-                            state.update_line(line_id)?;
-                        }
-                        continue
-                    }
-                    // No room and no finalized lines. Wait until a line becomes finalized.
-                    state.finalize_notifier.subscribe()
-                };
-                match notifications.recv().await {
-                    Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
-                    Err(broadcast::error::RecvError::Closed) => panic!("CLI notifier dropped"),
+        self.new_line_with_prefix(String::new(), initial_text.to_string())
+    }
+
+    /// Like [`Cli::new_line`], but the resulting line's rendered text starts with `prefix`, which is never
+    /// truncated; only `label` is sanitized and fitted to the remaining width.
+    async fn new_line_with_prefix<'a>(&'a self, prefix: String, label: String) -> io::Result<LineHandle<'a>> {
+        // make room for the line
+        loop {
+            let mut notifications = {
+                let mut state = self.state.lock();
+                if state.make_room()? {
+                    state.stdout.flush()?;
+                    break
                 }
-                //TODO also listen for terminal resize events
+                state.stdout.flush()?;
+                // No room and no finalized lines. Wait until a line becomes finalized or the terminal is
+                // resized; `Cli`'s `watch_resize` task keeps `term_size` current and wakes us via this same
+                // notifier regardless of whether we're the only one waiting.
+                state.finalize_notifier.subscribe()
+            };
+            match notifications.recv().await {
+                Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => panic!("CLI notifier dropped"),
             }
-            let mut state = self.state.lock();
-            // get an unused ID
-            let mut id = state.new_line_id;
+        }
+        let mut state = self.state.lock();
+        // get an unused ID
+        let mut id = state.new_line_id;
+        state.new_line_id = LineId(state.new_line_id.0.wrapping_add(1));
+        while state.lines.iter().any(|line| line.id == id) {
+            id = state.new_line_id;
             state.new_line_id = LineId(state.new_line_id.0.wrapping_add(1));
-            while state.lines.iter().any(|line| line.id == id) {
-                id = state.new_line_id;
-                state.new_line_id = LineId(state.new_line_id.0.wrapping_add(1));
-            }
-            // print the new line
-            if let Some(selected_line) = state.selected_line {
-                // Moves the cursor to the end of the lines managed by this value.
-                let selected_idx = state.lines.iter().position(|line| line.id == selected_line).expect("line not found");
-                let line_diff = state.lines.len() - 1 - selected_idx;
-                crossterm::execute!(
-                    state.stdout,
-                    crossterm::cursor::MoveToNextLine(line_diff.try_into().expect("terminal too large")),
-                    crossterm::style::Print("\r\n"),
-                )?;
-                state.selected_line = None;
-            }
-            state.lines.push(LineState {
-                finalized: false,
-                id, text,
-            });
-            state.update_line(id)?;
-            Ok(LineHandle { id, cli: self })
         }
+        // print the new line
+        if let Some(selected_line) = state.selected_line {
+            // Moves the cursor to the end of the lines managed by this value.
+            let selected_idx = state.lines.iter().position(|line| line.id == selected_line).expect("line not found");
+            let line_diff = state.lines.len() - 1 - selected_idx;
+            crossterm::queue!(
+                state.stdout,
+                crossterm::cursor::MoveToNextLine(line_diff.try_into().expect("terminal too large")),
+                crossterm::style::Print("\r\n"),
+            )?;
+            state.selected_line = None;
+        }
+        state.lines.push(LineState {
+            finalized: false,
+            id, prefix, label,
+            rate_limiter: None,
+        });
+        state.update_line(id, true)?;
+        state.stdout.flush()?;
+        Ok(LineHandle { id, cli: self })
     }
 
     /// Runs the given task to completion, displaying its progress in a new line below any existing lines.
     ///
     /// After the task is done, `done_label` is displayed as the final label of the task line. To have the label depend on the task's output, use [`Cli::run_with`].
     ///
-    /// # Correctness
-    ///
-    /// The task's `Display` implementation is called each time the progress bar is updated. Returning text that's wider than the remainder of the terminal after the 7-columns-wide percentage indicator or contains newlines or other control codes may cause the entire `Cli` to display incorrectly. The same restriction applies to `done_label`.
+    /// The `[ 42%]`/`[done]` indicator is always shown in full; `task`'s and `done_label`'s `Display` output is
+    /// sanitized and truncated to fit the remaining terminal width.
     pub async fn run<T>(&self, task: impl Task<T> + fmt::Display, done_label: impl fmt::Display) -> io::Result<T> {
         self.run_with(task, |_| done_label).await
     }
@@ -194,38 +496,66 @@ impl Cli {
     ///
     /// After the task is done, `done_label` is called with a reference to the task's output to display the final label of the task line.
     ///
-    /// # Correctness
-    ///
-    /// The task's `Display` implementation is called each time the progress bar is updated. Returning text that's wider than the remainder of the terminal after the 7-columns-wide percentage indicator or contains newlines or other control codes may cause the entire `Cli` to display incorrectly. The same restriction applies to `done_label`.
+    /// The `[ 42%]`/`[done]` indicator is always shown in full; `task`'s and `done_label`'s `Display` output is
+    /// sanitized and truncated to fit the remaining terminal width.
     pub async fn run_with<T, A: Task<T> + fmt::Display, L: fmt::Display, F: FnOnce(&T) -> L>(&self, mut task: A, done_label: F) -> io::Result<T> {
-        let line = self.new_line(format!("[  0%] {task}")).await?;
+        let (show_eta, bar_style) = { let state = self.state.lock(); (state.show_eta, state.bar_style) };
+        let mut estimator = Estimator::default();
+        estimator.push(task.progress());
+        let line = self.new_line_with_prefix(self.progress_prefix(bar_style, task.progress()), task.to_string()).await?;
         loop {
             match task.run().await {
                 Ok(result) => {
-                    line.replace(format!("[done] {}", done_label(&result)))?;
+                    line.set_text("[done] ".to_owned(), done_label(&result).to_string())?;
                     break Ok(result)
                 }
                 Err(next_task) => {
                     task = next_task;
-                    line.replace(format!("[{:>3}%] {task}", u8::from(task.progress())))?;
+                    let percent = task.progress();
+                    estimator.push(percent);
+                    let suffix = if show_eta { estimator.suffix() } else { String::new() };
+                    line.set_text(self.progress_prefix(bar_style, percent), format!("{task}{suffix}"))?;
                 }
             }
         }
     }
+
+    /// Runs all the given tasks concurrently, each on its own line, and returns their results once all of them are
+    /// done, in the same order as `tasks`.
+    ///
+    /// Unlike calling [`Cli::run`] once per task in a loop, the tasks don't wait for each other to complete before
+    /// making progress; they only wait on each other for a line to become available if the terminal is too short to
+    /// fit all of them at once.
+    pub async fn run_all<T, A: Task<T> + fmt::Display, L: fmt::Display>(&self, tasks: impl IntoIterator<Item = (A, L)>) -> io::Result<Vec<T>> {
+        futures::future::join_all(tasks.into_iter().map(|(task, done_label)| self.run(task, done_label))).await.into_iter().collect()
+    }
+
+    /// Renders the `[ 42%]` or `[=====-----] 50%`-style prefix for `run`/`run_with`, depending on `bar_style`.
+    fn progress_prefix(&self, bar_style: Option<BarStyle>, percent: Percent) -> String {
+        match bar_style {
+            Some(style) => {
+                let term_cols = self.state.lock().term_size.0;
+                render_bar(style, percent, term_cols)
+            }
+            None => format!("[{:>3}%] ", u8::from(percent)),
+        }
+    }
 }
 
 impl Drop for Cli {
     fn drop(&mut self) {
-        let state = self.state.get_mut();
+        self.resize_task.abort();
+        let mut state = self.state.lock();
         if let Some(selected_line) = state.selected_line {
             // Moves the cursor to the end of the lines managed by this value.
             let selected_idx = state.lines.iter().position(|line| line.id == selected_line).expect("line not found");
             let line_diff = state.lines.len() - 1 - selected_idx;
-            let _ = crossterm::execute!(
+            let _ = crossterm::queue!(
                 state.stdout,
                 crossterm::cursor::MoveToNextLine(line_diff.try_into().expect("terminal too large")),
                 crossterm::style::Print("\r\n"),
             );
+            let _ = state.stdout.flush();
         }
         let _ = disable_raw_mode();
     }
@@ -238,7 +568,13 @@ struct LineId(usize);
 struct LineState {
     id: LineId,
     finalized: bool,
-    text: String,
+    /// Never truncated, and always drawn in full: the `[ 42%]`/`[done]` indicator (or whatever a caller passes as
+    /// their own prefix).
+    prefix: String,
+    /// Sanitized and truncated to fit the terminal width (minus `prefix`) each time the line is drawn.
+    label: String,
+    /// Lazily created the first time this line is throttled, so a line's first redraw is never delayed.
+    rate_limiter: Option<RateLimiter>,
 }
 
 /// A handle to a line.
@@ -253,13 +589,25 @@ pub struct LineHandle<'a> {
 impl<'a> LineHandle<'a> {
     /// Replaces the contents of this line with the given text.
     ///
-    /// # Correctness
+    /// Subject to the `Cli`'s configured refresh rate (see [`Cli::with_refresh_rate`]): if this line was redrawn too
+    /// recently, the new text is stored but not drawn immediately. It will still appear the next time this line is
+    /// redrawn, and is guaranteed to appear once this handle is dropped.
     ///
-    /// If `new_text` is wider than the terminal or contains newlines or other control codes, the entire `Cli` may display incorrectly.
+    /// `new_text` is sanitized (control characters and newlines are stripped) and truncated with an ellipsis if it
+    /// doesn't fit the terminal width; it is re-truncated on every redraw, so it adapts to terminal resizes.
     pub fn replace(&self, new_text: impl fmt::Display) -> io::Result<()> {
+        self.set_text(String::new(), new_text.to_string())
+    }
+
+    /// Like [`LineHandle::replace`], but `prefix` is never truncated; only `label` is sanitized and fitted to the
+    /// remaining width.
+    fn set_text(&self, prefix: String, label: String) -> io::Result<()> {
         let mut state = self.cli.state.lock();
-        state.lines.iter_mut().find(|line| line.id == self.id).expect("line not found").text = new_text.to_string();
-        state.update_line(self.id)
+        let line = state.lines.iter_mut().find(|line| line.id == self.id).expect("line not found");
+        line.prefix = prefix;
+        line.label = label;
+        state.update_line(self.id, false)?;
+        state.stdout.flush()
     }
 }
 
@@ -270,6 +618,122 @@ impl<'a> Drop for LineHandle<'a> {
     fn drop(&mut self) {
         let mut state = self.cli.state.lock();
         state.lines.iter_mut().find(|line| line.id == self.id).expect("line not found").finalized = true;
+        // force a redraw so the line's final text is never lost to throttling
+        let _ = state.update_line(self.id, true);
+        let _ = state.stdout.flush();
         let _ = state.finalize_notifier.send(());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_and_truncate_passes_short_labels_through() {
+        assert_eq!(sanitize_and_truncate("hello", 10), "hello");
+    }
+
+    #[test]
+    fn sanitize_and_truncate_strips_control_characters() {
+        assert_eq!(sanitize_and_truncate("hel\nlo\tworld", 80), "helloworld");
+    }
+
+    #[test]
+    fn sanitize_and_truncate_adds_ellipsis_when_cutting() {
+        assert_eq!(sanitize_and_truncate("hello world", 8), "hello w…");
+    }
+
+    #[test]
+    fn sanitize_and_truncate_counts_display_width_not_graphemes() {
+        // each "本" is 2 columns wide, so only 3 fit in a budget of 7 (6 columns plus the ellipsis)
+        assert_eq!(sanitize_and_truncate("本本本本本", 7), "本本本…");
+    }
+
+    #[test]
+    fn sanitize_and_truncate_handles_zero_width() {
+        assert_eq!(sanitize_and_truncate("hello", 0), "");
+    }
+
+    #[test]
+    fn estimator_has_no_rate_or_eta_with_fewer_than_two_samples() {
+        let mut estimator = Estimator::default();
+        assert_eq!(estimator.rate(), None);
+        assert_eq!(estimator.eta(), None);
+        estimator.push(Percent::new(10));
+        assert_eq!(estimator.rate(), None);
+        assert_eq!(estimator.eta(), None);
+    }
+
+    #[test]
+    fn estimator_has_no_rate_when_progress_is_unchanged() {
+        let mut estimator = Estimator::default();
+        estimator.samples.push_back((Instant::now() - Duration::from_secs(1), Percent::new(50)));
+        estimator.samples.push_back((Instant::now(), Percent::new(50)));
+        assert_eq!(estimator.rate(), None);
+    }
+
+    #[test]
+    fn estimator_computes_rate_and_eta_for_steady_progress() {
+        let mut estimator = Estimator::default();
+        estimator.samples.push_back((Instant::now() - Duration::from_secs(10), Percent::new(0)));
+        estimator.samples.push_back((Instant::now(), Percent::new(50)));
+        // the gap between the two `Instant::now()` calls above is never exactly 10s, so compare with an epsilon
+        // rather than asserting exact equality on a timing-derived float
+        assert!((estimator.rate().unwrap() - 5.0).abs() < 0.01);
+        assert!((estimator.eta().unwrap().as_secs_f64() - 10.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn estimator_has_no_eta_when_progress_has_stalled_or_regressed() {
+        let mut estimator = Estimator::default();
+        estimator.samples.push_back((Instant::now() - Duration::from_secs(10), Percent::new(50)));
+        estimator.samples.push_back((Instant::now(), Percent::new(20)));
+        assert!(estimator.rate().unwrap() < 0.0);
+        assert_eq!(estimator.eta(), None);
+    }
+
+    #[test]
+    fn rate_limiter_allows_the_first_redraw_immediately() {
+        let mut limiter = RateLimiter::new(1.0);
+        assert!(limiter.try_consume());
+    }
+
+    #[test]
+    fn rate_limiter_throttles_bursts_beyond_capacity() {
+        let mut limiter = RateLimiter::new(1.0);
+        assert!(limiter.try_consume());
+        assert!(!limiter.try_consume());
+    }
+
+    #[test]
+    fn rate_limiter_refills_over_time() {
+        let mut limiter = RateLimiter::new(1.0);
+        assert!(limiter.try_consume());
+        limiter.last_refill -= Duration::from_secs(1);
+        assert!(limiter.try_consume());
+    }
+
+    #[test]
+    fn render_bar_is_empty_at_0_percent() {
+        let bar = render_bar(BarStyle::default(), Percent::default(), 80);
+        assert!(bar.starts_with('['), "{bar:?}");
+        assert!(bar.ends_with("] 0% "), "{bar:?}");
+        assert!(!bar.contains('='), "{bar:?}");
+    }
+
+    #[test]
+    fn render_bar_is_full_at_100_percent() {
+        let bar = render_bar(BarStyle::default(), Percent::MAX, 80);
+        assert!(bar.ends_with("] 100% "), "{bar:?}");
+        assert!(!bar.contains('-'), "{bar:?}");
+    }
+
+    #[test]
+    fn render_bar_never_exceeds_term_cols_even_when_too_narrow_to_fit_the_overhead() {
+        for term_cols in 0..30 {
+            let bar = render_bar(BarStyle::default(), Percent::new(50), term_cols);
+            assert!(bar.width() <= usize::from(term_cols), "rendered {:?} ({} cols wide) for term_cols = {term_cols}", bar, bar.width());
+        }
+    }
+}
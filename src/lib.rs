@@ -24,6 +24,7 @@ use {
 
 #[cfg(feature = "cli")] pub mod cli;
 mod std_types;
+pub use std_types::Weighted;
 
 /// A type representing a percentage.
 ///
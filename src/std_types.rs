@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use crate::{
     Percent,
     Progress,
@@ -14,3 +15,75 @@ impl Progress for (usize, usize) {
         Percent::fraction(self.0, self.1)
     }
 }
+
+impl<P: Progress> Progress for [P] {
+    /// The average of the slice's elements' progress, rounded down. An empty slice is defined as 100% complete,
+    /// consistent with the `0 / 0 = 100%` rule for [`Percent::fraction`].
+    fn progress(&self) -> Percent {
+        if self.is_empty() { return Percent::MAX }
+        let sum = self.iter().map(|item| u64::from(u8::from(item.progress()))).sum::<u64>();
+        Percent::new(u8::try_from(sum / u64::try_from(self.len()).expect("slice too large")).expect("average of percentages above 100"))
+    }
+}
+
+impl<P: Progress> Progress for Vec<P> {
+    fn progress(&self) -> Percent {
+        self.as_slice().progress()
+    }
+}
+
+impl<P: Progress, const N: usize> Progress for [P; N] {
+    fn progress(&self) -> Percent {
+        self.as_slice().progress()
+    }
+}
+
+/// A collection of sub-tasks, each with an associated weight, whose [`Progress`] is the weight-weighted average of
+/// the sub-tasks' own progress. A sub-task that makes up 90% of the total weight dominates the result accordingly.
+#[derive(Debug, Clone)]
+pub struct Weighted<P>(pub Vec<(u32, P)>);
+
+impl<P: Progress> Progress for Weighted<P> {
+    /// Computed as `sum(weight_i * percent_i) / sum(weight_i)`, rounded down. Defined as 100% if the total weight is
+    /// zero (including an empty collection), consistent with the `0 / 0 = 100%` rule for [`Percent::fraction`].
+    fn progress(&self) -> Percent {
+        let total_weight = self.0.iter().map(|&(weight, _)| u64::from(weight)).sum::<u64>();
+        if total_weight == 0 { return Percent::MAX }
+        let weighted_sum = self.0.iter().map(|(weight, item)| u64::from(*weight) * u64::from(u8::from(item.progress()))).sum::<u64>();
+        Percent::new(u8::try_from(weighted_sum / total_weight).expect("weighted average of percentages above 100"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_slice_is_complete() {
+        assert_eq!(Progress::progress(&Vec::<bool>::new()), Percent::MAX);
+    }
+
+    #[test]
+    fn slice_progress_is_the_average_rounded_down() {
+        let tasks = [true, false, false];
+        assert_eq!(tasks.progress(), Percent::new(33));
+    }
+
+    #[test]
+    fn array_progress_matches_slice_progress() {
+        let tasks: [(usize, usize); 2] = [(1, 2), (1, 4)];
+        assert_eq!(tasks.progress(), tasks.to_vec().progress());
+    }
+
+    #[test]
+    fn weighted_with_zero_total_weight_is_complete() {
+        assert_eq!(Weighted::<bool>(vec![]).progress(), Percent::MAX);
+        assert_eq!(Weighted(vec![(0, true), (0, false)]).progress(), Percent::MAX);
+    }
+
+    #[test]
+    fn weighted_progress_favors_the_heavier_sub_task() {
+        let weighted = Weighted(vec![(9, true), (1, false)]);
+        assert_eq!(weighted.progress(), Percent::new(90));
+    }
+}